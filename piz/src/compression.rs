@@ -0,0 +1,111 @@
+use std::io::{self, Read};
+
+use crate::result::*;
+
+// 4.4.5  compression method:
+//
+// Only the methods piz can actually decode (optionally, behind cargo
+// features) get a named variant; everything else round-trips through
+// `Other` so callers can still report which method an archive used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
+    Stored,
+    Deflate,
+    Deflate64,
+    Bzip2,
+    Zstd,
+    Other(u16),
+}
+
+impl From<u16> for CompressionMethod {
+    fn from(method: u16) -> Self {
+        match method {
+            0 => CompressionMethod::Stored,
+            8 => CompressionMethod::Deflate,
+            9 => CompressionMethod::Deflate64,
+            12 => CompressionMethod::Bzip2,
+            93 => CompressionMethod::Zstd,
+            other => CompressionMethod::Other(other),
+        }
+    }
+}
+
+/// A `Read` over an entry's raw, still-compressed bytes (sliced directly
+/// out of the archive's mmap) that yields the decompressed stream.
+/// Variants whose cargo feature is off are treated the same as methods
+/// piz has never heard of.
+pub enum EntryReader<'a> {
+    Stored(&'a [u8]),
+    #[cfg(feature = "deflate-flate2")]
+    Deflate(flate2::read::DeflateDecoder<&'a [u8]>),
+    #[cfg(feature = "deflate64")]
+    Deflate64(deflate64::Deflate64Decoder<&'a [u8]>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(bzip2::read::BzDecoder<&'a [u8]>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::read::Decoder<'a, io::BufReader<&'a [u8]>>),
+}
+
+impl<'a> EntryReader<'a> {
+    pub fn new(method: CompressionMethod, compressed: &'a [u8]) -> ZipResult<Self> {
+        match method {
+            CompressionMethod::Stored => Ok(EntryReader::Stored(compressed)),
+            #[cfg(feature = "deflate-flate2")]
+            CompressionMethod::Deflate => Ok(EntryReader::Deflate(
+                flate2::read::DeflateDecoder::new(compressed),
+            )),
+            #[cfg(feature = "deflate64")]
+            CompressionMethod::Deflate64 => Ok(EntryReader::Deflate64(
+                deflate64::Deflate64Decoder::new(compressed),
+            )),
+            #[cfg(feature = "bzip2")]
+            CompressionMethod::Bzip2 => {
+                Ok(EntryReader::Bzip2(bzip2::read::BzDecoder::new(compressed)))
+            }
+            #[cfg(feature = "zstd")]
+            CompressionMethod::Zstd => Ok(EntryReader::Zstd(
+                zstd::stream::read::Decoder::new(compressed).map_err(ZipError::Io)?,
+            )),
+            other => Err(ZipError::UnsupportedCompression(other)),
+        }
+    }
+}
+
+impl<'a> Read for EntryReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            EntryReader::Stored(r) => r.read(buf),
+            #[cfg(feature = "deflate-flate2")]
+            EntryReader::Deflate(r) => r.read(buf),
+            #[cfg(feature = "deflate64")]
+            EntryReader::Deflate64(r) => r.read(buf),
+            #[cfg(feature = "bzip2")]
+            EntryReader::Bzip2(r) => r.read(buf),
+            #[cfg(feature = "zstd")]
+            EntryReader::Zstd(r) => r.read(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_entries_read_back_unchanged() {
+        let data = b"hello, piz";
+        let mut reader = EntryReader::new(CompressionMethod::Stored, &data[..]).unwrap();
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn unsupported_methods_are_rejected() {
+        let err = EntryReader::new(CompressionMethod::Other(99), &[][..]).unwrap_err();
+        assert!(matches!(
+            err,
+            ZipError::UnsupportedCompression(CompressionMethod::Other(99))
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+use std::convert::TryInto;
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, NewBlockCipher};
+use aes::{Aes128, Aes192, Aes256};
+use hmac::{Hmac, Mac, NewMac};
+use pbkdf2::pbkdf2;
+use sha1::Sha1;
+
+use crate::result::*;
+use crate::spec::ExtraFieldEntries;
+
+// 4.5.2  -AES Extra Data Field (0x9901):
+const AES_EXTRA_ID: u16 = 0x9901;
+const TRADITIONAL_HEADER_SIZE: usize = 12;
+const AES_AUTH_CODE_SIZE: usize = 10;
+
+/// The three rolling CRC32-derived keys that drive PKWARE's traditional
+/// ("ZipCrypto") stream cipher. 6.3  Traditional PKWARE Encryption.
+struct ZipCryptoKeys(u32, u32, u32);
+
+impl ZipCryptoKeys {
+    fn from_password(password: &[u8]) -> Self {
+        let mut keys = ZipCryptoKeys(0x12345678, 0x23456789, 0x34567890);
+        for &byte in password {
+            keys.update(byte);
+        }
+        keys
+    }
+
+    fn update(&mut self, byte: u8) {
+        self.0 = crc32_update(self.0, byte);
+        self.1 = self.1.wrapping_add(self.0 & 0xff);
+        self.1 = self.1.wrapping_mul(134775813).wrapping_add(1);
+        self.2 = crc32_update(self.2, (self.1 >> 24) as u8);
+    }
+
+    fn decrypt_byte(&self) -> u8 {
+        let temp = (self.2 | 2) as u16;
+        (temp.wrapping_mul(temp ^ 1) >> 8) as u8
+    }
+
+    fn decrypt(&mut self, byte: u8) -> u8 {
+        let plain = byte ^ self.decrypt_byte();
+        self.update(plain);
+        plain
+    }
+}
+
+fn crc32_update(crc: u32, byte: u8) -> u32 {
+    (crc >> 8) ^ CRC32_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize]
+}
+
+// The standard CRC-32 (IEEE 802.3, polynomial 0xEDB88320) table, as used
+// both to checksum entries and to drive ZipCryptoKeys::update above.
+static CRC32_TABLE: [u32; 256] = build_crc32_table();
+
+const fn build_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+/// Decrypts a traditionally (ZipCrypto) encrypted entry. `data` is the
+/// 12-byte encryption header followed by the compressed payload;
+/// `check_byte` is the high byte of the entry's CRC-32 (or, with a data
+/// descriptor, its DOS mod-time) per 6.3's "password verification".
+pub fn decrypt_traditional<'a>(
+    data: &'a [u8],
+    password: &[u8],
+    check_byte: u8,
+) -> ZipResult<(Vec<u8>, usize)> {
+    if data.len() < TRADITIONAL_HEADER_SIZE {
+        return Err(ZipError::InvalidArchive(
+            "Encrypted entry shorter than its header",
+        ));
+    }
+    let mut keys = ZipCryptoKeys::from_password(password);
+    let mut header = [0u8; TRADITIONAL_HEADER_SIZE];
+    for (i, &byte) in data[..TRADITIONAL_HEADER_SIZE].iter().enumerate() {
+        header[i] = keys.decrypt(byte);
+    }
+    if header[TRADITIONAL_HEADER_SIZE - 1] != check_byte {
+        return Err(ZipError::InvalidPassword);
+    }
+
+    let payload = &data[TRADITIONAL_HEADER_SIZE..];
+    let decrypted = payload.iter().map(|&byte| keys.decrypt(byte)).collect();
+    Ok((decrypted, TRADITIONAL_HEADER_SIZE))
+}
+
+/// The AES strength (and therefore key/salt sizes) recorded in the
+/// 0x9901 extra field's strength byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AesStrength {
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl AesStrength {
+    fn salt_size(self) -> usize {
+        match self {
+            AesStrength::Aes128 => 8,
+            AesStrength::Aes192 => 12,
+            AesStrength::Aes256 => 16,
+        }
+    }
+
+    fn key_size(self) -> usize {
+        match self {
+            AesStrength::Aes128 => 16,
+            AesStrength::Aes192 => 24,
+            AesStrength::Aes256 => 32,
+        }
+    }
+}
+
+impl std::convert::TryFrom<u8> for AesStrength {
+    type Error = ZipError;
+
+    fn try_from(value: u8) -> ZipResult<Self> {
+        match value {
+            1 => Ok(AesStrength::Aes128),
+            2 => Ok(AesStrength::Aes192),
+            3 => Ok(AesStrength::Aes256),
+            _ => Err(ZipError::InvalidArchive("Unknown AES encryption strength")),
+        }
+    }
+}
+
+/// The vendor version and *actual* compression method carried in an
+/// entry's 0x9901 extra field (AE-x entries report 99 as their nominal
+/// method and stash the real one here instead).
+pub struct AesExtraField {
+    pub vendor_version: u16,
+    pub strength: AesStrength,
+    pub actual_compression_method: u16,
+}
+
+impl AesExtraField {
+    /// Looks for the 0x9901 AES record in `extra_field`.
+    pub fn find(extra_field: &[u8]) -> ZipResult<Option<Self>> {
+        for entry in ExtraFieldEntries::new(extra_field) {
+            let (header_id, data) = entry?;
+            if header_id != AES_EXTRA_ID {
+                continue;
+            }
+            if data.len() != 7 {
+                return Err(ZipError::InvalidArchive("Malformed AES extra field"));
+            }
+            let vendor_version = u16::from_le_bytes(data[0..2].try_into().unwrap());
+            let strength = data[4].try_into()?;
+            let actual_compression_method = u16::from_le_bytes(data[5..7].try_into().unwrap());
+            return Ok(Some(AesExtraField {
+                vendor_version,
+                strength,
+                actual_compression_method,
+            }));
+        }
+        Ok(None)
+    }
+}
+
+// Avoids short-circuiting on the first mismatching byte, so a timing
+// side channel can't be used to recover the authentication code.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// Decrypts a WinZip AE-1/AE-2 (0x9901) encrypted entry. `data` is the
+/// salt, 2-byte password verification value, AES-CTR ciphertext and
+/// trailing 10-byte authentication code, exactly as stored in the archive.
+pub fn decrypt_aes(data: &[u8], password: &[u8], strength: AesStrength) -> ZipResult<Vec<u8>> {
+    let salt_size = strength.salt_size();
+    let key_size = strength.key_size();
+    if data.len() < salt_size + 2 + AES_AUTH_CODE_SIZE {
+        return Err(ZipError::InvalidArchive(
+            "AES-encrypted entry shorter than its header",
+        ));
+    }
+
+    let salt = &data[..salt_size];
+    let password_verify = &data[salt_size..salt_size + 2];
+    let ciphertext = &data[salt_size + 2..data.len() - AES_AUTH_CODE_SIZE];
+    let auth_code = &data[data.len() - AES_AUTH_CODE_SIZE..];
+
+    // 2.2.1  WinZip AE-x key derivation: PBKDF2-HMAC-SHA1 over the salt,
+    // producing the decryption key, the HMAC-SHA1 authentication key and
+    // the 2-byte password verification value, back to back.
+    let mut derived = vec![0u8; key_size * 2 + 2];
+    pbkdf2::<HmacSha1>(password, salt, 1000, &mut derived);
+    let (decryption_key, rest) = derived.split_at(key_size);
+    let (authentication_key, derived_verify) = rest.split_at(key_size);
+
+    if derived_verify != password_verify {
+        return Err(ZipError::InvalidPassword);
+    }
+
+    // WinZip truncates the HMAC-SHA1 tag to 10 bytes (2.2.1), so we can't
+    // hand `auth_code` to `Mac::verify` directly: it compares against the
+    // full 20-byte tag and would reject on length alone. Compute the tag
+    // ourselves and compare only the bytes WinZip actually kept.
+    let mut mac =
+        HmacSha1::new_from_slice(authentication_key).expect("HMAC-SHA1 accepts keys of any length");
+    mac.update(ciphertext);
+    let tag = mac.finalize().into_bytes();
+    if !constant_time_eq(&tag[..AES_AUTH_CODE_SIZE], auth_code) {
+        return Err(ZipError::InvalidArchive("AES authentication code mismatch"));
+    }
+
+    let mut plaintext = ciphertext.to_vec();
+    match strength {
+        AesStrength::Aes128 => {
+            ctr_decrypt_in_place(&Aes128::new(GenericArray::from_slice(decryption_key)), &mut plaintext)
+        }
+        AesStrength::Aes192 => {
+            ctr_decrypt_in_place(&Aes192::new(GenericArray::from_slice(decryption_key)), &mut plaintext)
+        }
+        AesStrength::Aes256 => {
+            ctr_decrypt_in_place(&Aes256::new(GenericArray::from_slice(decryption_key)), &mut plaintext)
+        }
+    }
+    Ok(plaintext)
+}
+
+// 2.2.1  WinZip's AE-x CTR mode increments a 16-byte *little-endian*
+// counter starting at 1 - the opposite byte order from the `aes` crate's
+// `Aes*Ctr` stream ciphers (a standard big-endian counter), so this is
+// done by hand over the raw block cipher instead of reaching for those.
+fn ctr_decrypt_in_place<C: BlockEncrypt>(cipher: &C, data: &mut [u8]) {
+    let mut counter: u128 = 1;
+    for block in data.chunks_mut(16) {
+        let mut keystream = GenericArray::from(counter.to_le_bytes());
+        cipher.encrypt_block(&mut keystream);
+        for (byte, key) in block.iter_mut().zip(keystream.iter()) {
+            *byte ^= key;
+        }
+        counter = counter.wrapping_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ZipCrypto's key stream is symmetric: encrypting runs the exact same
+    // decrypt_byte()/update() sequence, just fed the plaintext byte
+    // instead of the ciphertext byte.
+    fn encrypt_traditional(password: &[u8], header: [u8; TRADITIONAL_HEADER_SIZE], payload: &[u8]) -> Vec<u8> {
+        let mut keys = ZipCryptoKeys::from_password(password);
+        let mut data = Vec::with_capacity(TRADITIONAL_HEADER_SIZE + payload.len());
+        for &byte in header.iter().chain(payload) {
+            data.push(byte ^ keys.decrypt_byte());
+            keys.update(byte);
+        }
+        data
+    }
+
+    #[test]
+    fn traditional_round_trips_and_verifies_the_check_byte() {
+        let password = b"hunter2";
+        let check_byte = 0xab;
+        let payload = b"the quick brown fox";
+        let mut header = [0u8; TRADITIONAL_HEADER_SIZE];
+        header[TRADITIONAL_HEADER_SIZE - 1] = check_byte;
+
+        let data = encrypt_traditional(password, header, payload);
+        let (decrypted, header_size) = decrypt_traditional(&data, password, check_byte).unwrap();
+        assert_eq!(header_size, TRADITIONAL_HEADER_SIZE);
+        assert_eq!(decrypted, payload);
+    }
+
+    #[test]
+    fn traditional_rejects_the_wrong_password() {
+        let check_byte = 0xab;
+        let mut header = [0u8; TRADITIONAL_HEADER_SIZE];
+        header[TRADITIONAL_HEADER_SIZE - 1] = check_byte;
+        let data = encrypt_traditional(b"hunter2", header, b"payload");
+
+        let err = decrypt_traditional(&data, b"wrong password", check_byte).unwrap_err();
+        assert!(matches!(err, ZipError::InvalidPassword));
+    }
+
+    // A known-answer vector, independent of this module's own CTR code:
+    // generated with Python's `cryptography` library by deriving the
+    // PBKDF2 key material and then hand-rolling WinZip's little-endian
+    // block counter over raw AES-ECB, rather than reusing decrypt_aes's
+    // own keystream logic (which a self-consistent round trip can't
+    // catch a counter-endianness bug against). Two full 16-byte blocks,
+    // so a big-endian-vs-little-endian counter mixup changes the second
+    // block's plaintext.
+    #[test]
+    fn aes_decrypts_a_known_answer_vector() {
+        let password = b"hunter2";
+        let strength = AesStrength::Aes128;
+        #[rustfmt::skip]
+        let data: [u8; 52] = [
+            0, 1, 2, 3, 4, 5, 6, 7,
+            93, 21,
+            61, 227, 73, 211, 67, 82, 177, 96, 73, 219, 136, 218, 243, 112, 48, 152,
+            105, 234, 79, 241, 62, 232, 60, 26, 229, 93, 221, 27, 227, 39, 30, 96,
+            189, 63, 187, 30, 56, 17, 115, 238, 78, 11,
+        ];
+        let expected = b"The quick brown fox jumps over!!";
+
+        let decrypted = decrypt_aes(&data, password, strength).unwrap();
+        assert_eq!(decrypted, expected);
+    }
+
+    #[test]
+    fn aes_extra_field_parses_the_0x9901_record() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&AES_EXTRA_ID.to_le_bytes());
+        data.extend_from_slice(&7u16.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes()); // vendor_version (AE-2)
+        data.extend_from_slice(b"AE"); // vendor id
+        data.push(3); // strength: AES-256
+        data.extend_from_slice(&8u16.to_le_bytes()); // actual compression method (deflate)
+
+        let aes = AesExtraField::find(&data).unwrap().unwrap();
+        assert_eq!(aes.vendor_version, 2);
+        assert_eq!(aes.strength, AesStrength::Aes256);
+        assert_eq!(aes.actual_compression_method, 8);
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::{Cursor, Read};
+
+use crate::arch::usize;
+use crate::compression::{CompressionMethod, EntryReader};
+use crate::crypto::{decrypt_aes, decrypt_traditional, AesExtraField};
+use crate::result::*;
+use crate::spec;
+
+pub use crate::spec::CentralDirectoryEntry as FileMetadata;
+
+/// A parsed zip archive: the central directory, read once up front from
+/// `mapping`, plus everything needed to read each entry's data back out
+/// of it on demand.
+pub struct ZipArchive<'a> {
+    mapping: &'a [u8],
+    entries: Vec<FileMetadata<'a>>,
+}
+
+impl<'a> ZipArchive<'a> {
+    pub fn new(mapping: &'a [u8]) -> ZipResult<Self> {
+        let eocdr_offset = spec::find_eocdr(mapping)?;
+        let eocdr = spec::EndOfCentralDirectory::parse(&mapping[eocdr_offset..])?;
+
+        // 4.3.16: a plain EOCDR's counts/offsets are authoritative unless
+        // they're saturated (meaning the real ones are in the Zip64 End
+        // Of Central Directory Record instead) or they don't actually
+        // locate a valid central directory in this file, which some
+        // writers leave the door open for even without saturating any
+        // field.
+        let classic_offset = eocdr.central_directory_offset as usize;
+        let classic_size = eocdr.central_directory_size as usize;
+        let classic_is_valid = classic_offset
+            .checked_add(classic_size)
+            .and_then(|end| mapping.get(classic_offset..end))
+            .map_or(false, |directory| {
+                eocdr.entries == 0 || directory.starts_with(b"PK\x01\x02")
+            });
+
+        let (entry_count, central_directory_offset, central_directory_size) =
+            if !eocdr.needs_zip64() && classic_is_valid {
+                (eocdr.entries as usize, classic_offset, classic_size)
+            } else {
+                let zip64_eocdr = spec::find_zip64_eocdr(mapping, eocdr_offset)?;
+                (
+                    usize(zip64_eocdr.entries)?,
+                    usize(zip64_eocdr.central_directory_offset)?,
+                    usize(zip64_eocdr.central_directory_size)?,
+                )
+            };
+
+        let central_directory_end = central_directory_offset
+            .checked_add(central_directory_size)
+            .ok_or(ZipError::InvalidArchive(
+                "Central directory runs past the end of the archive",
+            ))?;
+        let mut central_directory = mapping
+            .get(central_directory_offset..central_directory_end)
+            .ok_or(ZipError::InvalidArchive(
+                "Central directory runs past the end of the archive",
+            ))?;
+
+        let mut entries = Vec::with_capacity(entry_count);
+        for _ in 0..entry_count {
+            entries.push(FileMetadata::parse_and_consume(&mut central_directory)?);
+        }
+
+        Ok(Self { mapping, entries })
+    }
+
+    /// The archive's entries, in central directory order.
+    pub fn entries(&self) -> &[FileMetadata<'a>] {
+        &self.entries
+    }
+
+    /// Reads an entry's decompressed bytes, sliced zero-copy out of the
+    /// mapping and fed through the codec its `compression_method` calls
+    /// for. Fails with `ZipError::Encrypted` for encrypted entries; use
+    /// `read_with_password` for those instead.
+    pub fn read(&self, entry: &FileMetadata<'a>) -> ZipResult<EntryReader<'a>> {
+        if entry.encrypted {
+            return Err(ZipError::Encrypted);
+        }
+        EntryReader::new(entry.compression(), self.compressed_bytes(entry)?)
+    }
+
+    /// Reads a password-protected entry: decrypts it with whichever of
+    /// traditional ZipCrypto or WinZip AE-1/AE-2 its extra field calls
+    /// for, then decompresses it. Decryption produces an owned buffer
+    /// rather than the zero-copy slice `read` returns, so the whole
+    /// entry is read eagerly here.
+    pub fn read_with_password(
+        &self,
+        entry: &FileMetadata<'a>,
+        password: &[u8],
+    ) -> ZipResult<Cursor<Vec<u8>>> {
+        if !entry.encrypted {
+            let mut buf = Vec::new();
+            self.read(entry)?.read_to_end(&mut buf).map_err(ZipError::Io)?;
+            return Ok(Cursor::new(buf));
+        }
+
+        let compressed = self.compressed_bytes(entry)?;
+        let (plain, method) = match AesExtraField::find(entry.extra_field)? {
+            Some(aes) => (
+                decrypt_aes(compressed, password, aes.strength)?,
+                CompressionMethod::from(aes.actual_compression_method),
+            ),
+            None => {
+                // 6.3: when bit 3 of the general purpose flag is set, the
+                // CRC wasn't known yet at encryption time, so the header's
+                // last byte was checked against the high byte of the DOS
+                // last-modified time instead.
+                let has_data_descriptor = entry.flags & (1 << 3) != 0;
+                let check_byte = if has_data_descriptor {
+                    (entry.last_mod_time >> 8) as u8
+                } else {
+                    (entry.crc32 >> 24) as u8
+                };
+                let (plain, _header_size) = decrypt_traditional(compressed, password, check_byte)?;
+                (plain, entry.compression())
+            }
+        };
+
+        let mut buf = Vec::new();
+        EntryReader::new(method, plain.as_slice())?
+            .read_to_end(&mut buf)
+            .map_err(ZipError::Io)?;
+        Ok(Cursor::new(buf))
+    }
+
+    fn compressed_bytes(&self, entry: &FileMetadata<'a>) -> ZipResult<&'a [u8]> {
+        let data_offset = spec::local_file_data_offset(self.mapping, usize(entry.offset)?)?;
+        let data_end = data_offset
+            .checked_add(usize(entry.compressed_size)?)
+            .ok_or(ZipError::InvalidArchive(
+                "Entry data runs past the end of the archive",
+            ))?;
+        self.mapping.get(data_offset..data_end).ok_or(ZipError::InvalidArchive(
+            "Entry data runs past the end of the archive",
+        ))
+    }
+}
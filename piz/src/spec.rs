@@ -2,9 +2,10 @@ use std::borrow::Cow;
 use std::convert::TryInto;
 
 use codepage_437::*;
-use twoway::{find_bytes, rfind_bytes};
+use twoway::rfind_bytes;
 
 use crate::arch::usize;
+use crate::compression::CompressionMethod;
 use crate::result::*;
 
 const EOCDR_MAGIC: [u8; 4] = [b'P', b'K', 5, 6];
@@ -83,14 +84,79 @@ impl<'a> EndOfCentralDirectory<'a> {
             file_comment,
         })
     }
+
+    /// True if any field is saturated with the `0xFFFF`/`0xFFFFFFFF`
+    /// sentinel 4.3.16 uses to signal "see the Zip64 record instead".
+    pub fn needs_zip64(&self) -> bool {
+        self.disk_number == u16::max_value()
+            || self.disk_with_central_directory == u16::max_value()
+            || self.entries_on_this_disk == u16::max_value()
+            || self.entries == u16::max_value()
+            || self.central_directory_size == u32::max_value()
+            || self.central_directory_offset == u32::max_value()
+    }
 }
 
+// The fixed-size part of the record (4.3.16), not counting the comment.
+const EOCDR_FIXED_SIZE: usize = 22;
+// The comment length is a u16, so it can never push the record further
+// back than this from the end of the file.
+const MAX_EOCDR_SEARCH_SIZE: usize = EOCDR_FIXED_SIZE + u16::max_value() as usize;
+
+/// Finds the offset of the real End Of Central Directory Record, scanning
+/// backwards and validating each `PK\x05\x06` match instead of trusting
+/// the first one (which may just be a file comment's contents).
 pub fn find_eocdr(mapping: &[u8]) -> ZipResult<usize> {
-    rfind_bytes(mapping, &EOCDR_MAGIC).ok_or(ZipError::InvalidArchive(
+    let search_start = mapping.len().saturating_sub(MAX_EOCDR_SEARCH_SIZE);
+    let mut search_end = mapping.len();
+
+    while search_end >= search_start + EOCDR_MAGIC.len() {
+        let candidate = match rfind_bytes(&mapping[search_start..search_end], &EOCDR_MAGIC) {
+            Some(offset) => search_start + offset,
+            None => break,
+        };
+        if is_valid_eocdr_candidate(mapping, candidate) {
+            return Ok(candidate);
+        }
+        // rfind_bytes just gave us the rightmost match in the window, so
+        // any other candidate must lie strictly to its left.
+        search_end = candidate;
+    }
+
+    Err(ZipError::InvalidArchive(
         "Couldn't find End Of Central Directory Record",
     ))
 }
 
+/// Checks that a `PK\x05\x06` match at `position` is plausibly the real
+/// record: its comment length must account for exactly the remaining
+/// bytes in the file, and a Zip64 locator must precede it if it says one
+/// is needed.
+fn is_valid_eocdr_candidate(mapping: &[u8], position: usize) -> bool {
+    if mapping.len() - position < EOCDR_FIXED_SIZE {
+        return false;
+    }
+    let record = &mapping[position..];
+    let comment_length = u16::from_le_bytes(record[20..22].try_into().unwrap()) as usize;
+    if comment_length != mapping.len() - (position + EOCDR_FIXED_SIZE) {
+        return false;
+    }
+
+    // Safe to parse now: the comment length check above guarantees
+    // `record` has exactly the bytes EndOfCentralDirectory::parse expects
+    // to slice as the comment.
+    let eocdr = match EndOfCentralDirectory::parse(record) {
+        Ok(eocdr) => eocdr,
+        Err(_) => return false,
+    };
+
+    if eocdr.needs_zip64() && find_zip64_eocdr_locator(mapping, position).is_err() {
+        return false;
+    }
+
+    true
+}
+
 #[derive(Debug)]
 pub struct Zip64EndOfCentralDirectoryLocator {
     pub disk_with_central_directory: u32,
@@ -130,6 +196,22 @@ impl Zip64EndOfCentralDirectoryLocator {
     }
 }
 
+/// Locates and parses the Zip64 End Of Central Directory Locator (4.3.15)
+/// that must immediately precede the EOCDR at `eocdr_offset`.
+pub fn find_zip64_eocdr_locator(
+    mapping: &[u8],
+    eocdr_offset: usize,
+) -> ZipResult<Zip64EndOfCentralDirectoryLocator> {
+    let locator_start = eocdr_offset
+        .checked_sub(Zip64EndOfCentralDirectoryLocator::size_in_file())
+        .ok_or(ZipError::InvalidArchive(
+            "Not enough room for a Zip64 End Of Central Directory Locator",
+        ))?;
+    Zip64EndOfCentralDirectoryLocator::parse(&mapping[locator_start..]).ok_or(
+        ZipError::InvalidArchive("Invalid Zip64 End Of Central Directory Locator"),
+    )
+}
+
 #[derive(Debug)]
 pub struct Zip64EndOfCentralDirectory<'a> {
     pub source_version: u16,
@@ -221,15 +303,215 @@ impl<'a> Zip64EndOfCentralDirectory<'a> {
     }
 }
 
-pub fn find_zip64_eocdr(mapping: &[u8]) -> ZipResult<usize> {
-    find_bytes(mapping, &ZIP64_EOCDR_MAGIC).ok_or(ZipError::InvalidArchive(
-        "Couldn't find zip64 End Of Central Directory Record",
+/// Resolves the Zip64 End Of Central Directory Record for the archive
+/// whose classic EOCDR sits at `eocdr_offset`: finds its locator (4.3.15),
+/// which must immediately precede the EOCDR, then parses the record at
+/// the offset it points to (rather than scanning for one, which could
+/// find a `PK\x06\x06` that just happens to appear inside entry data).
+pub fn find_zip64_eocdr<'a>(
+    mapping: &'a [u8],
+    eocdr_offset: usize,
+) -> ZipResult<Zip64EndOfCentralDirectory<'a>> {
+    let locator = find_zip64_eocdr_locator(mapping, eocdr_offset)?;
+    let zip64_eocdr_offset = usize(locator.zip64_eocdr_offset)?;
+    let record = mapping
+        .get(zip64_eocdr_offset..)
+        .ok_or(ZipError::InvalidArchive(
+            "Zip64 End Of Central Directory Record offset runs past the end of the archive",
+        ))?;
+    if record.get(..4) != Some(&ZIP64_EOCDR_MAGIC[..]) {
+        return Err(ZipError::InvalidArchive(
+            "Zip64 End Of Central Directory Locator doesn't point at a Zip64 End Of Central Directory Record",
+        ));
+    }
+    Zip64EndOfCentralDirectory::parse(record)
+}
+
+// 4.5.3  -Zip64 Extended Information Extra Field (0x0001):
+//
+// The fields here only show up, and only in this order, when the
+// corresponding field in the fixed-size part of the central directory
+// entry was saturated with its 0xFFFF/0xFFFFFFFF sentinel.
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+/// Iterates the `(header_id, data)` records of an entry's extra field
+/// (4.5): a sequence of 2-byte id, 2-byte length, then that many bytes
+/// of data. Shared by every extra field piz looks inside, so the bounds
+/// check only has to be right in one place.
+pub(crate) struct ExtraFieldEntries<'a>(&'a [u8]);
+
+impl<'a> ExtraFieldEntries<'a> {
+    pub(crate) fn new(extra_field: &'a [u8]) -> Self {
+        Self(extra_field)
+    }
+}
+
+impl<'a> Iterator for ExtraFieldEntries<'a> {
+    type Item = ZipResult<(u16, &'a [u8])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.len() < 4 {
+            return None;
+        }
+        let header_id = read_u16(&mut self.0);
+        let data_size = match usize(u64::from(read_u16(&mut self.0))) {
+            Ok(data_size) => data_size,
+            Err(e) => return Some(Err(e)),
+        };
+        if self.0.len() < data_size {
+            return Some(Err(ZipError::InvalidArchive(
+                "Extra field data runs past its slice",
+            )));
+        }
+        let (data, rest) = self.0.split_at(data_size);
+        self.0 = rest;
+        Some(Ok((header_id, data)))
+    }
+}
+
+/// Resolves the 32-bit `compressed_size`/`uncompressed_size`/`offset`/
+/// `disk_number` read from the fixed part of a central directory entry
+/// against the Zip64 extended information extra field, if present.
+///
+/// Each field is only read from the extra field if its 32-bit (or, for
+/// `disk_number`, 16-bit) counterpart was saturated; fields that weren't
+/// saturated are passed through unchanged. The four fields that can be
+/// overridden always appear in this fixed order: uncompressed size,
+/// compressed size, local header offset, disk start number.
+fn resolve_zip64_extra(
+    extra_field: &[u8],
+    compressed_size: u32,
+    uncompressed_size: u32,
+    offset: u32,
+    disk_number: u16,
+) -> ZipResult<(u64, u64, u64, u32)> {
+    let mut resolved_compressed_size = u64::from(compressed_size);
+    let mut resolved_uncompressed_size = u64::from(uncompressed_size);
+    let mut resolved_offset = u64::from(offset);
+    let mut resolved_disk_number = u32::from(disk_number);
+
+    for entry in ExtraFieldEntries::new(extra_field) {
+        let (header_id, data) = entry?;
+        if header_id != ZIP64_EXTRA_ID {
+            continue;
+        }
+
+        let mut expected_size = 0;
+        if uncompressed_size == u32::max_value() {
+            expected_size += 8;
+        }
+        if compressed_size == u32::max_value() {
+            expected_size += 8;
+        }
+        if offset == u32::max_value() {
+            expected_size += 8;
+        }
+        if disk_number == u16::max_value() {
+            expected_size += 4;
+        }
+        if data.len() != expected_size {
+            return Err(ZipError::InvalidArchive(
+                "Zip64 extended information field doesn't match the sentinel fields it should override",
+            ));
+        }
+
+        let mut data = data;
+        if uncompressed_size == u32::max_value() {
+            resolved_uncompressed_size = read_u64(&mut data);
+        }
+        if compressed_size == u32::max_value() {
+            resolved_compressed_size = read_u64(&mut data);
+        }
+        if offset == u32::max_value() {
+            resolved_offset = read_u64(&mut data);
+        }
+        if disk_number == u16::max_value() {
+            resolved_disk_number = read_u32(&mut data);
+        }
+    }
+
+    Ok((
+        resolved_compressed_size,
+        resolved_uncompressed_size,
+        resolved_offset,
+        resolved_disk_number,
     ))
 }
 
-pub struct CentralDirectoryEntry {}
+const EXTENDED_TIMESTAMP_ID: u16 = 0x5455;
+
+// 0x5455 Info-ZIP extended timestamp: a flags byte (bit 0 set means
+// "mtime follows") then up to three little-endian i32 Unix times for
+// mtime, atime, ctime. Only mtime is read here.
+fn find_extended_timestamp(extra_field: &[u8]) -> ZipResult<Option<i64>> {
+    for entry in ExtraFieldEntries::new(extra_field) {
+        let (header_id, data) = entry?;
+        if header_id != EXTENDED_TIMESTAMP_ID || data.len() < 5 {
+            continue;
+        }
+        if data[0] & 0x1 == 0 {
+            continue;
+        }
+        let mtime = i32::from_le_bytes(data[1..5].try_into().unwrap());
+        return Ok(Some(i64::from(mtime)));
+    }
+    Ok(None)
+}
+
+// Howard Hinnant's `days_from_civil`: the number of days since the Unix
+// epoch for a given proleptic Gregorian calendar date. Used to decode the
+// DOS date/time pair into a Unix timestamp when no extended timestamp
+// extra field is present.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let year_of_era = y - era * 400;
+    let month_since_march = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * month_since_march + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Decodes the DOS `last_mod_date`/`last_mod_time` pair (4.4.6) into a
+/// Unix timestamp, as a fallback for entries without an extended
+/// timestamp extra field.
+fn dos_to_unix_timestamp(date: u16, time: u16) -> i64 {
+    let year = 1980 + i64::from((date >> 9) & 0x7f);
+    let month = u32::from((date >> 5) & 0x0f);
+    let day = u32::from(date & 0x1f);
+    let hour = i64::from((time >> 11) & 0x1f);
+    let minute = i64::from((time >> 5) & 0x3f);
+    let second = i64::from(time & 0x1f) * 2;
+
+    days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second
+}
 
-impl CentralDirectoryEntry {
+#[derive(Debug)]
+pub struct CentralDirectoryEntry<'a> {
+    pub source_version: u16,
+    pub minimum_extract_version: u16,
+    pub flags: u16,
+    pub compression_method: u16,
+    pub last_mod_time: u16,
+    pub last_mod_date: u16,
+    pub crc32: u32,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    pub file_name: Cow<'a, str>,
+    pub extra_field: &'a [u8],
+    pub file_comment: Cow<'a, str>,
+    pub disk_number: u32,
+    pub internal_file_attributes: u16,
+    pub external_file_attributes: u32,
+    pub offset: u64,
+    pub encrypted: bool,
+    /// Modification time as a Unix timestamp, preferring the extended
+    /// timestamp extra field over the (1980-2107-only, 2-second
+    /// resolution) DOS date/time pair.
+    pub modified: i64,
+}
+
+impl<'a> CentralDirectoryEntry<'a> {
     pub fn parse_and_consume(entry: &mut &[u8]) -> ZipResult<Self> {
         // 4.3.12  Central directory structure:
         //
@@ -282,7 +564,7 @@ impl CentralDirectoryEntry {
         let disk_number = read_u16(entry);
         let internal_file_attributes = read_u16(entry);
         let external_file_attributes = read_u32(entry);
-        let offset = read_u32(entry) as u64;
+        let offset = read_u32(entry);
         let (file_name_raw, remaining) = entry.split_at(file_name_length);
         let (extra_field, remaining) = remaining.split_at(extra_field_length);
         let (file_comment_raw, remaining) = remaining.split_at(file_comment_length);
@@ -299,8 +581,229 @@ impl CentralDirectoryEntry {
             Cow::borrow_from_cp437(file_name_raw, &CP437_CONTROL)
         };
 
+        let file_comment: Cow<str> = if is_utf8 {
+            Cow::from(std::str::from_utf8(file_comment_raw).map_err(|e| ZipError::Encoding(e))?)
+        } else {
+            Cow::borrow_from_cp437(file_comment_raw, &CP437_CONTROL)
+        };
+
         log::trace!("Entry for {:?}", file_name);
 
-        Ok(Self {})
+        let (compressed_size, uncompressed_size, offset, disk_number) = resolve_zip64_extra(
+            extra_field,
+            compressed_size,
+            uncompressed_size,
+            offset,
+            disk_number,
+        )?;
+
+        let modified = find_extended_timestamp(extra_field)?
+            .unwrap_or_else(|| dos_to_unix_timestamp(last_mod_date, last_mod_time));
+
+        Ok(Self {
+            source_version,
+            minimum_extract_version,
+            flags,
+            compression_method,
+            last_mod_time,
+            last_mod_date,
+            crc32,
+            compressed_size,
+            uncompressed_size,
+            file_name,
+            extra_field,
+            file_comment,
+            disk_number,
+            internal_file_attributes,
+            external_file_attributes,
+            offset,
+            encrypted,
+            modified,
+        })
+    }
+
+    /// The entry's compression method, decoded from the raw `compression_method`
+    /// field for use with [`crate::compression::EntryReader`].
+    pub fn compression(&self) -> CompressionMethod {
+        CompressionMethod::from(self.compression_method)
+    }
+
+    /// The Unix file mode stashed in the high 16 bits of
+    /// `external_file_attributes` by Unix-aware zip tools, if present.
+    pub fn unix_mode(&self) -> Option<u32> {
+        let mode = self.external_file_attributes >> 16;
+        if mode != 0 {
+            Some(mode)
+        } else {
+            None
+        }
+    }
+
+    /// True for directories: entries whose name ends with the
+    /// conventional trailing `/`, or whose Unix mode, when one is
+    /// present, has the directory file-type bits set.
+    pub fn is_dir(&self) -> bool {
+        self.file_name.ends_with('/')
+            || self
+                .unix_mode()
+                .map_or(false, |mode| mode & 0o170_000 == 0o040_000)
+    }
+}
+
+const LOCAL_FILE_HEADER_MAGIC: [u8; 4] = [b'P', b'K', 3, 4];
+// 4.3.7: fixed-size part of the local file header, up to and including
+// the extra field length.
+const LOCAL_FILE_HEADER_FIXED_SIZE: usize = 30;
+
+/// Finds the offset of an entry's compressed data, which sits just past
+/// its local file header (4.3.7) at `offset` in the mapping. The central
+/// directory's copies of name/size/etc. are authoritative, so only the
+/// file name and extra field lengths are read back out of this header.
+pub fn local_file_data_offset(mapping: &[u8], offset: usize) -> ZipResult<usize> {
+    let header_end = offset
+        .checked_add(LOCAL_FILE_HEADER_FIXED_SIZE)
+        .ok_or(ZipError::InvalidArchive(
+            "Local file header runs past the end of the archive",
+        ))?;
+    let header = mapping
+        .get(offset..header_end)
+        .ok_or(ZipError::InvalidArchive(
+            "Local file header runs past the end of the archive",
+        ))?;
+    if header[..4] != LOCAL_FILE_HEADER_MAGIC {
+        return Err(ZipError::InvalidArchive("Invalid local file header"));
+    }
+    let file_name_length = usize(u64::from(u16::from_le_bytes(
+        header[26..28].try_into().unwrap(),
+    )))?;
+    let extra_field_length = usize(u64::from(u16::from_le_bytes(
+        header[28..30].try_into().unwrap(),
+    )))?;
+    header_end
+        .checked_add(file_name_length)
+        .and_then(|n| n.checked_add(extra_field_length))
+        .ok_or(ZipError::InvalidArchive(
+            "Local file header runs past the end of the archive",
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain_eocdr() -> EndOfCentralDirectory<'static> {
+        EndOfCentralDirectory {
+            disk_number: 0,
+            disk_with_central_directory: 0,
+            entries_on_this_disk: 3,
+            entries: 3,
+            central_directory_size: 100,
+            central_directory_offset: 200,
+            file_comment: &[],
+        }
+    }
+
+    #[test]
+    fn needs_zip64_is_false_for_a_plain_archive() {
+        assert!(!plain_eocdr().needs_zip64());
+    }
+
+    #[test]
+    fn needs_zip64_fires_on_any_saturated_field() {
+        let mut eocdr = plain_eocdr();
+        eocdr.entries = u16::max_value();
+        assert!(eocdr.needs_zip64());
+
+        let mut eocdr = plain_eocdr();
+        eocdr.central_directory_offset = u32::max_value();
+        assert!(eocdr.needs_zip64());
+    }
+
+    // Header id (2 bytes LE) + data length (2 bytes LE) + data.
+    fn extra_field_record(header_id: u16, data: &[u8]) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend_from_slice(&header_id.to_le_bytes());
+        record.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        record.extend_from_slice(data);
+        record
+    }
+
+    #[test]
+    fn resolve_zip64_extra_overrides_only_the_saturated_fields_in_order() {
+        // uncompressed_size and offset are saturated; compressed_size and
+        // disk_number aren't, so the record holds just two u64s: the first
+        // and third of the four fields, in that order.
+        let mut data = Vec::new();
+        data.extend_from_slice(&123_456_789_012u64.to_le_bytes()); // uncompressed_size
+        data.extend_from_slice(&987_654_321_098u64.to_le_bytes()); // offset
+        let extra_field = extra_field_record(ZIP64_EXTRA_ID, &data);
+
+        let (compressed_size, uncompressed_size, offset, disk_number) =
+            resolve_zip64_extra(&extra_field, 10, u32::max_value(), u32::max_value(), 2).unwrap();
+        assert_eq!(compressed_size, 10);
+        assert_eq!(uncompressed_size, 123_456_789_012);
+        assert_eq!(offset, 987_654_321_098);
+        assert_eq!(disk_number, 2);
+    }
+
+    #[test]
+    fn resolve_zip64_extra_rejects_a_size_mismatch() {
+        // Only uncompressed_size is saturated, so 8 bytes are expected;
+        // this record supplies 16.
+        let data = [0u8; 16];
+        let extra_field = extra_field_record(ZIP64_EXTRA_ID, &data);
+
+        let result = resolve_zip64_extra(&extra_field, 10, u32::max_value(), 20, 2);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn dos_to_unix_timestamp_decodes_a_known_date() {
+        // 2021-01-01 00:00:00: year 41 (1980+41), month 1, day 1, all-zero time.
+        let date = (41 << 9) | (1 << 5) | 1;
+        assert_eq!(dos_to_unix_timestamp(date, 0), 1_609_459_200);
+    }
+
+    #[test]
+    fn find_extended_timestamp_reads_only_mtime() {
+        let mut data = Vec::new();
+        data.push(0b111); // mtime, atime and ctime flags all set
+        data.extend_from_slice(&1_609_459_200i32.to_le_bytes());
+        data.extend_from_slice(&0i32.to_le_bytes());
+        data.extend_from_slice(&0i32.to_le_bytes());
+        let extra_field = extra_field_record(EXTENDED_TIMESTAMP_ID, &data);
+
+        assert_eq!(
+            find_extended_timestamp(&extra_field).unwrap(),
+            Some(1_609_459_200)
+        );
+    }
+
+    fn build_eocdr(comment: &[u8]) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend_from_slice(&EOCDR_MAGIC);
+        record.extend_from_slice(&0u16.to_le_bytes()); // disk_number
+        record.extend_from_slice(&0u16.to_le_bytes()); // disk_with_central_directory
+        record.extend_from_slice(&0u16.to_le_bytes()); // entries_on_this_disk
+        record.extend_from_slice(&0u16.to_le_bytes()); // entries
+        record.extend_from_slice(&0u32.to_le_bytes()); // central_directory_size
+        record.extend_from_slice(&0u32.to_le_bytes()); // central_directory_offset
+        record.extend_from_slice(&(comment.len() as u16).to_le_bytes());
+        record.extend_from_slice(comment);
+        record
+    }
+
+    #[test]
+    fn find_eocdr_skips_a_fake_signature_in_the_comment() {
+        let comment = b"look, a fake signature: PK\x05\x06 right there";
+        let mapping = build_eocdr(comment);
+
+        assert_eq!(find_eocdr(&mapping).unwrap(), 0);
+    }
+
+    #[test]
+    fn find_eocdr_fails_when_nothing_validates() {
+        let mapping = b"not a zip file at all".to_vec();
+        assert!(find_eocdr(&mapping).is_err());
     }
 }